@@ -1,20 +1,32 @@
 use std::rc::Rc;
 
-use anymap::AnyMap;
 use slab::Slab;
 
 use crate::{
     mrc::Mrc,
+    scope,
     store::Store,
-    subscriber::{Callable, SubscriberId},
+    subscriber::{Callable, Selector, SubscriberId, Subscription},
 };
 
 pub(crate) struct Context<S> {
     pub(crate) store: Rc<S>,
-    pub(crate) subscribers: Slab<Box<dyn Callable<S>>>,
+    pub(crate) subscribers: Slab<Subscription<S>>,
+    cleanup: fn(&S),
 }
 
 impl<S: Store> Context<S> {
+    pub(crate) fn new() -> Self {
+        let mut store = S::new();
+        store.on_init();
+
+        Self {
+            store: Rc::new(store),
+            subscribers: Default::default(),
+            cleanup: S::on_cleanup,
+        }
+    }
+
     /// Apply a function to state, returning if it has changed or not.
     pub(crate) fn reduce(&mut self, f: impl FnOnce(&mut S)) -> bool {
         let previous = Rc::clone(&self.store);
@@ -31,11 +43,61 @@ impl<S: Store> Context<S> {
         changed
     }
 
+    /// Apply multiple `reduce`-style mutations as a single transaction. Each mutation is
+    /// still applied individually, but whether anything actually changed is judged once,
+    /// by comparing the state from before the batch to the state after it, so `S::changed`
+    /// runs at most once and subscribers are notified at most once per batch (and not at
+    /// all if the batch's net effect leaves the state unchanged).
+    pub(crate) fn reduce_batch(&mut self, f: impl FnOnce(&mut Batch<S>)) -> bool {
+        let previous = Rc::clone(&self.store);
+
+        f(&mut Batch {
+            store: &mut self.store,
+        });
+
+        let changed = previous.as_ref() != self.store.as_ref();
+
+        if changed {
+            Rc::make_mut(&mut self.store).changed();
+            self.notify_subscribers();
+        }
+
+        changed
+    }
+
     pub(crate) fn subscribe(&mut self, on_change: impl Callable<S>) -> SubscriberId<S> {
         // Notify subscriber with inital state.
         on_change.call(Rc::clone(&self.store));
 
-        let key = self.subscribers.insert(Box::new(on_change));
+        let key = self
+            .subscribers
+            .insert(Subscription::Callback(Box::new(on_change)));
+
+        SubscriberId {
+            key,
+            _store_type: Default::default(),
+        }
+    }
+
+    /// Subscribe to just the slice of state derived by `selector`. `on_change` is called
+    /// with the initial state and, after that, only when `selector` produces a value that
+    /// differs from the last one it produced.
+    pub(crate) fn subscribe_selector<T, F>(
+        &mut self,
+        selector: F,
+        on_change: impl Callable<S> + 'static,
+    ) -> SubscriberId<S>
+    where
+        F: Fn(&S) -> T + 'static,
+        T: PartialEq + Clone + 'static,
+    {
+        // Notify subscriber with inital state.
+        on_change.call(Rc::clone(&self.store));
+
+        let selector = Selector::new(selector, on_change, &self.store);
+        let key = self
+            .subscribers
+            .insert(Subscription::Selector(Box::new(selector)));
 
         SubscriberId {
             key,
@@ -49,35 +111,78 @@ impl<S: Store> Context<S> {
 
     pub(crate) fn notify_subscribers(&self) {
         for (_, subscriber) in &self.subscribers {
-            subscriber.call(Rc::clone(&self.store));
+            subscriber.notify(&self.store);
         }
     }
 }
 
+/// Mutable handle passed to the closure given to [`Context::reduce_batch`].
+///
+/// Unlike [`Context::reduce`], applying a mutation here doesn't decide on its own whether
+/// anything changed — `reduce_batch` judges that once, for the batch as a whole, by
+/// comparing state from before the batch to state after it.
+pub(crate) struct Batch<'a, S> {
+    store: &'a mut Rc<S>,
+}
+
+impl<'a, S: Store> Batch<'a, S> {
+    pub(crate) fn reduce(&mut self, f: impl FnOnce(&mut S)) {
+        f(Rc::make_mut(self.store));
+    }
+}
+
+impl<S> Drop for Context<S> {
+    /// Runs `S::on_cleanup` exactly once, as this context's store is dropped alongside it.
+    /// Runs through the shared `Rc<S>` rather than requiring unique ownership of it, since
+    /// subscribers are expected to still be holding their own clone at this point.
+    fn drop(&mut self) {
+        (self.cleanup)(&self.store);
+    }
+}
+
+/// Get the `Context<S>` for the current scope (see [`crate::scope`]), initializing it if
+/// this is the first access.
 pub(crate) fn get_or_init<S: Store>() -> Mrc<Context<S>> {
-    thread_local! {
-        /// Stores all shared state.
-        static CONTEXTS: Mrc<AnyMap> = Mrc::new(AnyMap::new());
-    }
-
-    CONTEXTS
-        .try_with(|context| context.clone())
-        .expect("CONTEXTS thread local key init failed")
-        .with_mut(|contexts| {
-            contexts
-                .entry::<Mrc<Context<S>>>()
-                .or_insert_with(|| {
-                    Mrc::new(Context {
-                        store: Rc::new(S::new()),
-                        subscribers: Default::default(),
-                    })
-                })
-                .clone()
-        })
+    scope::current().get_or_init::<S>()
+}
+
+/// Remove the `Context<S>` for the current scope, if any, dropping its subscribers and
+/// running `S::on_cleanup` once the last reference to it goes away.
+pub fn clear<S: Store>() {
+    scope::current().clear::<S>();
+}
+
+/// Remove every store context in the current scope, dropping all subscribers and running
+/// each store's `S::on_cleanup` once the last reference to it goes away.
+pub fn clear_all() {
+    scope::current().clear_all();
+}
+
+/// Clears every store context in the current scope when dropped.
+///
+/// Handy in tests, where a thread-local scope otherwise persists state from one `#[test]`
+/// into the next:
+///
+/// ```ignore
+/// #[test]
+/// fn my_test() {
+///     let _reset = ResetOnDrop;
+///     // ...
+/// }
+/// ```
+#[derive(Default)]
+pub struct ResetOnDrop;
+
+impl Drop for ResetOnDrop {
+    fn drop(&mut self) {
+        clear_all();
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::cell::Cell;
+
     use super::*;
 
     #[derive(Clone, PartialEq)]
@@ -94,6 +199,7 @@ mod tests {
 
     #[test]
     fn store_changed_is_called() {
+        let _reset = ResetOnDrop;
         let mut context = get_or_init::<TestState>();
 
         context.with_mut(|context| context.reduce(|state| state.0 += 1));
@@ -103,10 +209,194 @@ mod tests {
 
     #[test]
     fn store_changed_is_not_called_when_state_is_same() {
+        let _reset = ResetOnDrop;
         let mut context = get_or_init::<TestState>();
 
         context.with_mut(|context| context.reduce(|_| {}));
 
         assert!(context.borrow().store.0 == 0);
     }
+
+    #[test]
+    fn clear_drops_the_context() {
+        let _reset = ResetOnDrop;
+        let mut context = get_or_init::<TestState>();
+        context.with_mut(|context| context.reduce(|state| state.0 = 5));
+        drop(context);
+
+        clear::<TestState>();
+
+        let context = get_or_init::<TestState>();
+        assert!(context.borrow().store.0 == 0);
+    }
+
+    #[derive(Clone, PartialEq)]
+    struct CleanupState {
+        cleaned_up: Rc<Cell<bool>>,
+    }
+    impl Store for CleanupState {
+        fn new() -> Self {
+            Self {
+                cleaned_up: Rc::new(Cell::new(false)),
+            }
+        }
+
+        fn on_cleanup(&self) {
+            self.cleaned_up.set(true);
+        }
+    }
+
+    #[test]
+    fn clear_runs_on_cleanup_even_while_a_subscriber_still_holds_the_store() {
+        use std::cell::RefCell;
+
+        let _reset = ResetOnDrop;
+        let mut context = get_or_init::<CleanupState>();
+        let cleaned_up = context.borrow().store.cleaned_up.clone();
+
+        // Subscribers are expected to stash the `Rc<S>` they're handed (e.g. to render
+        // from later), so `Context` is not the sole owner of `store` once this runs.
+        let held = Rc::new(RefCell::new(None));
+        context.with_mut(|context| {
+            let held = Rc::clone(&held);
+            context.subscribe(move |state| *held.borrow_mut() = Some(state))
+        });
+        assert!(Rc::strong_count(&context.borrow().store) > 1);
+
+        drop(context);
+        clear::<CleanupState>();
+
+        assert!(cleaned_up.get());
+        drop(held);
+    }
+
+    #[derive(Clone, PartialEq)]
+    struct LifecycleState {
+        initialized: bool,
+    }
+    impl Store for LifecycleState {
+        fn new() -> Self {
+            Self { initialized: false }
+        }
+
+        fn on_init(&mut self) {
+            self.initialized = true;
+        }
+    }
+
+    #[test]
+    fn on_init_runs_once_before_first_use() {
+        let context = scope::Scope::new().get_or_init::<LifecycleState>();
+
+        assert!(context.borrow().store.initialized);
+    }
+
+    #[derive(Clone, PartialEq)]
+    struct PairState {
+        tracked: u32,
+        ignored: u32,
+    }
+    impl Store for PairState {
+        fn new() -> Self {
+            Self {
+                tracked: 0,
+                ignored: 0,
+            }
+        }
+    }
+
+    #[test]
+    fn subscribe_selector_only_fires_when_selected_value_changes() {
+        use std::cell::RefCell;
+
+        let _reset = ResetOnDrop;
+        let mut context = get_or_init::<PairState>();
+        let calls = Rc::new(RefCell::new(0));
+
+        context.with_mut(|context| {
+            let calls = Rc::clone(&calls);
+            context.subscribe_selector(|state: &PairState| state.tracked, move |_state| {
+                *calls.borrow_mut() += 1;
+            })
+        });
+        assert!(*calls.borrow() == 1); // initial notification
+
+        context.with_mut(|context| context.reduce(|state| state.ignored += 1));
+        context.with_mut(|context| context.notify_subscribers());
+        assert!(*calls.borrow() == 1);
+
+        context.with_mut(|context| context.reduce(|state| state.tracked += 1));
+        context.with_mut(|context| context.notify_subscribers());
+        assert!(*calls.borrow() == 2);
+    }
+
+    #[test]
+    fn reduce_batch_coalesces_changed_and_notifies_once() {
+        use std::cell::RefCell;
+
+        let _reset = ResetOnDrop;
+        let mut context = get_or_init::<TestState>();
+        let notifications = Rc::new(RefCell::new(0));
+
+        context.with_mut(|context| {
+            let notifications = Rc::clone(&notifications);
+            context.subscribe(move |_state| *notifications.borrow_mut() += 1)
+        });
+        assert!(*notifications.borrow() == 1); // initial notification
+
+        let changed = context.with_mut(|context| {
+            context.reduce_batch(|batch| {
+                batch.reduce(|state| state.0 += 1);
+                batch.reduce(|state| state.0 += 1);
+                batch.reduce(|state| state.0 += 1);
+            })
+        });
+
+        assert!(changed);
+        assert!(context.borrow().store.0 == 4); // 3 mutations + one `changed` bump
+        assert!(*notifications.borrow() == 2);
+    }
+
+    #[test]
+    fn reduce_batch_is_a_no_op_when_nothing_changes() {
+        let _reset = ResetOnDrop;
+        let mut context = get_or_init::<TestState>();
+
+        let changed = context.with_mut(|context| {
+            context.reduce_batch(|batch| {
+                batch.reduce(|_| {});
+                batch.reduce(|_| {});
+            })
+        });
+
+        assert!(!changed);
+        assert!(context.borrow().store.0 == 0);
+    }
+
+    #[test]
+    fn reduce_batch_is_a_no_op_when_the_net_effect_is_unchanged() {
+        use std::cell::RefCell;
+
+        let _reset = ResetOnDrop;
+        let mut context = get_or_init::<PairState>();
+        let notifications = Rc::new(RefCell::new(0));
+
+        context.with_mut(|context| {
+            let notifications = Rc::clone(&notifications);
+            context.subscribe(move |_state| *notifications.borrow_mut() += 1)
+        });
+        assert!(*notifications.borrow() == 1); // initial notification
+
+        // Individually each mutation changes the state, but the batch's net effect doesn't.
+        let changed = context.with_mut(|context| {
+            context.reduce_batch(|batch| {
+                batch.reduce(|state| state.tracked += 1);
+                batch.reduce(|state| state.tracked -= 1);
+            })
+        });
+
+        assert!(!changed);
+        assert!(context.borrow().store.tracked == 0);
+        assert!(*notifications.borrow() == 1);
+    }
 }
@@ -0,0 +1,9 @@
+mod context;
+mod mrc;
+pub mod scope;
+mod store;
+mod subscriber;
+
+pub use context::{clear, clear_all, ResetOnDrop};
+pub use scope::Scope;
+pub use store::Store;
@@ -0,0 +1,153 @@
+use std::cell::RefCell;
+
+use anymap::AnyMap;
+
+use crate::{context::Context, mrc::Mrc, store::Store};
+
+thread_local! {
+    /// Stack of entered scopes, innermost last. Empty when no scope has been entered.
+    static CURRENT: RefCell<Vec<Scope>> = RefCell::new(Vec::new());
+    /// The scope used when no scope has been entered, preserving the historical
+    /// single-scope-per-thread behavior as the default.
+    static GLOBAL: Scope = Scope::new();
+}
+
+/// An isolated collection of store contexts.
+///
+/// By default every store on a thread shares the single thread-global scope. A `Scope`
+/// lets independent pieces of code keep their own, isolated set of stores on the same
+/// thread, e.g. so that server-side rendering can service many requests concurrently
+/// without one request's state leaking into another's, or so that tests can each start
+/// from a blank slate.
+#[derive(Clone)]
+pub struct Scope {
+    contexts: Mrc<AnyMap>,
+}
+
+impl Scope {
+    /// Create a new, empty scope.
+    pub fn new() -> Self {
+        Self {
+            contexts: Mrc::new(AnyMap::new()),
+        }
+    }
+
+    /// Make this the current scope for the duration of `f`, restoring whichever scope
+    /// was current before (falling back to the thread-global scope) once `f` returns.
+    ///
+    /// The previous scope is restored even if `f` panics, so a panic while servicing one
+    /// request can't leak its scope onto whatever runs next on this thread.
+    pub fn enter<R>(&self, f: impl FnOnce() -> R) -> R {
+        CURRENT.with(|current| current.borrow_mut().push(self.clone()));
+        let _guard = PopCurrentOnDrop;
+        f()
+    }
+
+    pub(crate) fn get_or_init<S: Store>(&self) -> Mrc<Context<S>> {
+        self.contexts.clone().with_mut(|contexts| {
+            contexts
+                .entry::<Mrc<Context<S>>>()
+                .or_insert_with(|| Mrc::new(Context::new()))
+                .clone()
+        })
+    }
+
+    /// Remove this scope's `Context<S>`, if any, dropping its subscribers and running
+    /// `S::on_cleanup` once the last reference to it goes away.
+    pub(crate) fn clear<S: Store>(&self) {
+        self.contexts
+            .clone()
+            .with_mut(|contexts| contexts.remove::<Mrc<Context<S>>>());
+    }
+
+    /// Remove every context held by this scope.
+    pub(crate) fn clear_all(&self) {
+        self.contexts
+            .clone()
+            .with_mut(|contexts| *contexts = AnyMap::new());
+    }
+}
+
+impl Default for Scope {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Pops the innermost entered scope off `CURRENT` on drop, including on unwind, so
+/// `Scope::enter` restores the previous scope even when its closure panics.
+struct PopCurrentOnDrop;
+
+impl Drop for PopCurrentOnDrop {
+    fn drop(&mut self) {
+        CURRENT.with(|current| {
+            current.borrow_mut().pop();
+        });
+    }
+}
+
+/// Returns the currently entered scope, or the thread-global scope if none is entered.
+pub(crate) fn current() -> Scope {
+    CURRENT
+        .with(|current| current.borrow().last().cloned())
+        .unwrap_or_else(|| GLOBAL.with(Scope::clone))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, PartialEq)]
+    struct TestState(u32);
+    impl Store for TestState {
+        fn new() -> Self {
+            Self(0)
+        }
+    }
+
+    #[test]
+    fn scopes_are_isolated() {
+        let a = Scope::new();
+        let b = Scope::new();
+
+        a.enter(|| {
+            let mut context = a.get_or_init::<TestState>();
+            context.with_mut(|context| context.reduce(|state| state.0 += 1));
+        });
+
+        let context = b.get_or_init::<TestState>();
+        assert!(context.borrow().store.0 == 0);
+    }
+
+    #[test]
+    fn falls_back_to_global_scope_outside_enter() {
+        let global_before = current().get_or_init::<TestState>();
+        let global_after = current().get_or_init::<TestState>();
+
+        // Both resolve to the same context because neither call entered a scope.
+        assert!(std::ptr::eq(
+            global_before.borrow().store.as_ref() as *const TestState,
+            global_after.borrow().store.as_ref() as *const TestState,
+        ));
+    }
+
+    #[test]
+    fn enter_restores_previous_scope_even_if_f_panics() {
+        let a = Scope::new();
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            a.enter(|| panic!("boom"))
+        }));
+        assert!(result.is_err());
+
+        let from_a = a.get_or_init::<TestState>();
+        let from_current = current().get_or_init::<TestState>();
+
+        // If the panic had left `a` stuck as the current scope, both calls would resolve
+        // to the same context.
+        assert!(!std::ptr::eq(
+            from_a.borrow().store.as_ref() as *const TestState,
+            from_current.borrow().store.as_ref() as *const TestState,
+        ));
+    }
+}
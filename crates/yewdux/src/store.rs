@@ -0,0 +1,33 @@
+/// Interface for a state store.
+///
+/// This is automatically implemented for all types that implement `Default` +
+/// `Clone` + `PartialEq`, however it can also be implemented manually for more control over
+/// how the store is created and modified.
+pub trait Store: Clone + PartialEq + 'static {
+    /// Create this store.
+    fn new() -> Self;
+
+    /// Called after every change to this store.
+    fn changed(&mut self) {}
+
+    /// Called once, right after the store is created, before it is made available to any
+    /// subscriber. Use this for setup that depends on the store already being constructed
+    /// (e.g. opening a websocket or registering an interval timer).
+    fn on_init(&mut self) {}
+
+    /// Called once, when the store's context is torn down. Use this to release resources
+    /// acquired in [`on_init`](Store::on_init).
+    ///
+    /// Takes `&self` rather than `&mut self`: by the time a context is torn down,
+    /// subscribers are expected to be holding their own `Rc` clone of the store (that's the
+    /// whole point of handing them an `Rc<S>` rather than a `&S`), so cleanup can't rely on
+    /// unique ownership. Track any state `on_cleanup` needs to update through interior
+    /// mutability (e.g. a `Cell`/`RefCell` field) instead.
+    fn on_cleanup(&self) {}
+}
+
+impl<T: Default + Clone + PartialEq + 'static> Store for T {
+    fn new() -> Self {
+        Default::default()
+    }
+}
@@ -0,0 +1,87 @@
+use std::{cell::RefCell, marker::PhantomData, rc::Rc};
+
+/// Something that can be called whenever a store changes.
+pub(crate) trait Callable<S>: 'static {
+    fn call(&self, state: Rc<S>);
+}
+
+impl<S, F: Fn(Rc<S>) + 'static> Callable<S> for F {
+    fn call(&self, state: Rc<S>) {
+        self(state)
+    }
+}
+
+/// A single entry in a `Context`'s subscriber list.
+///
+/// Either a plain callback that runs on every change, or a [`Selector`] that only runs its
+/// callback when the value it derives from the store actually changes.
+pub(crate) enum Subscription<S> {
+    Callback(Box<dyn Callable<S>>),
+    Selector(Box<dyn SelectorSubscription<S>>),
+}
+
+impl<S: 'static> Subscription<S> {
+    pub(crate) fn notify(&self, state: &Rc<S>) {
+        match self {
+            Subscription::Callback(callback) => callback.call(Rc::clone(state)),
+            Subscription::Selector(selector) => selector.notify(state),
+        }
+    }
+}
+
+pub(crate) trait SelectorSubscription<S> {
+    /// Recompute the selected value and, if it changed since the last call, run the
+    /// callback with the new state.
+    fn notify(&self, state: &Rc<S>);
+}
+
+/// Pairs a selector function with the last value it produced, so repeated calls can tell
+/// whether the derived value actually changed.
+pub(crate) struct Selector<S, T, F, C> {
+    select: F,
+    on_change: C,
+    last: RefCell<T>,
+    _store_type: PhantomData<S>,
+}
+
+impl<S, T, F, C> Selector<S, T, F, C>
+where
+    F: Fn(&S) -> T,
+    T: PartialEq + Clone,
+{
+    pub(crate) fn new(select: F, on_change: C, state: &S) -> Self {
+        let last = RefCell::new(select(state));
+
+        Self {
+            select,
+            on_change,
+            last,
+            _store_type: PhantomData,
+        }
+    }
+}
+
+impl<S, T, F, C> SelectorSubscription<S> for Selector<S, T, F, C>
+where
+    F: Fn(&S) -> T,
+    T: PartialEq + Clone,
+    C: Callable<S>,
+{
+    fn notify(&self, state: &Rc<S>) {
+        let next = (self.select)(state);
+
+        if *self.last.borrow() != next {
+            *self.last.borrow_mut() = next;
+            self.on_change.call(Rc::clone(state));
+        }
+    }
+}
+
+/// Handle for a subscriber, returned by `Context::subscribe`/`Context::subscribe_selector`.
+///
+/// Dropping a `SubscriberId` does *not* remove the subscriber on its own; pass its `key` to
+/// `Context::unsubscribe` to do that.
+pub struct SubscriberId<S> {
+    pub(crate) key: usize,
+    pub(crate) _store_type: PhantomData<S>,
+}